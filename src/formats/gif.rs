@@ -0,0 +1,35 @@
+//! Metadata of GIF images.
+
+use std::io::BufRead;
+
+use byteorder::{ReadBytesExt, LittleEndian};
+
+use types::{Result, Dimensions};
+use traits::LoadableMetadata;
+
+/// Represents metadata of a GIF image.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    /// Image size.
+    pub dimensions: Dimensions,
+}
+
+impl LoadableMetadata for Metadata {
+    fn load<R: ?Sized + BufRead>(r: &mut R) -> Result<Metadata> {
+        // 3-byte signature ("GIF"), 3-byte version ("87a" or "89a"), then the
+        // logical screen descriptor: width and height as little-endian u16s
+        let mut header = [0; 6];
+        try!(r.read_exact(&mut header));
+        if &header[0..3] != b"GIF" {
+            return Err(invalid_format!("invalid GIF signature"));
+        }
+        if &header[3..6] != b"87a" && &header[3..6] != b"89a" {
+            return Err(invalid_format!("unsupported GIF version"));
+        }
+
+        let width = try_if_eof!(r.read_u16::<LittleEndian>(), "when reading logical screen width");
+        let height = try_if_eof!(r.read_u16::<LittleEndian>(), "when reading logical screen height");
+
+        Ok(Metadata { dimensions: (width, height).into() })
+    }
+}
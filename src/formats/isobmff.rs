@@ -0,0 +1,314 @@
+//! Metadata of ISO base media file format (ISO/IEC 14496-12) images, which
+//! covers HEIF/HEIC as produced by modern phone cameras.
+
+use std::io::BufRead;
+
+use byteorder::{ReadBytesExt, BigEndian};
+
+use types::{Result, Dimensions, Error};
+use traits::LoadableMetadata;
+
+const BRANDS: &'static [&'static [u8; 4]] = &[b"heic", b"heix", b"mif1", b"msf1"];
+
+/// Represents metadata of a HEIF/HEIC image.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    /// Image size.
+    pub dimensions: Dimensions,
+}
+
+// A single box header: its type and the range of its payload within the
+// stream, expressed as a remaining byte count so callers can bound reads of
+// nested boxes without tracking absolute offsets.
+struct BoxHeader {
+    box_type: [u8; 4],
+    header_len: u64,
+    payload_len: Option<u64>,
+}
+
+fn read_box_header<R: ?Sized + BufRead>(r: &mut R) -> Result<BoxHeader> {
+    let size = try_if_eof!(r.read_u32::<BigEndian>(), "when reading box size");
+    let box_type = {
+        let mut buf = [0; 4];
+        try!(r.read_exact(&mut buf));
+        buf
+    };
+
+    // A 32-bit size of 1 means the real size follows as a 64-bit field; a
+    // size of 0 means "extends to the end of the file", which we represent
+    // as an unbounded payload.
+    let (header_len, total_len) = match size {
+        0 => (8, None),
+        1 => {
+            let n = try_if_eof!(r.read_u64::<BigEndian>(), "when reading large box size");
+            if n < 16 {
+                return Err(invalid_format!("invalid 64-bit box size: {}", n));
+            }
+            (16, Some(n))
+        }
+        n if n < 8 => return Err(invalid_format!("invalid box size: {}", n)),
+        n => (8, Some(n as u64)),
+    };
+
+    let payload_len = total_len.map(|n| n - header_len);
+
+    Ok(BoxHeader { box_type: box_type, header_len: header_len, payload_len: payload_len })
+}
+
+// Reads boxes from `r` until `budget` bytes have been consumed (or the
+// stream ends, if `budget` is `None`), calling `f` with the type and
+// payload length of each one. `f` must consume exactly `payload_len` bytes
+// of the payload itself.
+fn walk_boxes<R: ?Sized + BufRead, F>(r: &mut R, budget: Option<u64>, mut f: F) -> Result<()>
+    where F: FnMut(&mut R, [u8; 4], Option<u64>) -> Result<()>
+{
+    let mut consumed = 0u64;
+    loop {
+        if let Some(budget) = budget {
+            if consumed >= budget {
+                return Ok(());
+            }
+        }
+
+        let header = match read_box_header(r) {
+            Ok(header) => header,
+            Err(Error::UnexpectedEof(..)) if budget.is_none() => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        try!(f(r, header.box_type, header.payload_len));
+
+        consumed += header.header_len + header.payload_len.unwrap_or(0);
+    }
+}
+
+fn skip_bytes<R: ?Sized + BufRead>(r: &mut R, mut n: u64) -> Result<()> {
+    let mut buf = [0u8; 4096];
+    while n > 0 {
+        let chunk = ::std::cmp::min(n, buf.len() as u64) as usize;
+        try!(r.read_exact(&mut buf[..chunk]));
+        n -= chunk as u64;
+    }
+    Ok(())
+}
+
+impl LoadableMetadata for Metadata {
+    fn load<R: ?Sized + BufRead>(r: &mut R) -> Result<Metadata> {
+        let mut brand_ok = false;
+        let mut dimensions = None;
+
+        // Walked by hand rather than via `walk_boxes`: once `ispe`
+        // dimensions have been found there's no need to read the rest of
+        // the file, which commonly includes a multi-megabyte `mdat` box
+        // (sometimes one whose size is 0, i.e. "extends to end of file" --
+        // legal per ISO 14496-12 and not an error, just nothing we need).
+        loop {
+            let header = match read_box_header(r) {
+                Ok(header) => header,
+                Err(Error::UnexpectedEof(..)) => break,
+                Err(e) => return Err(e),
+            };
+
+            match &header.box_type {
+                b"ftyp" => {
+                    let len = try!(header.payload_len.ok_or_else(
+                        || invalid_format!("ftyp box must not extend to end of file")));
+                    if try!(read_ftyp(r, len)) {
+                        brand_ok = true;
+                    }
+                }
+                b"meta" => {
+                    let len = try!(header.payload_len.ok_or_else(
+                        || invalid_format!("meta box must not extend to end of file")));
+                    if let Some(found) = try!(read_meta(r, len)) {
+                        dimensions = Some(found);
+                    }
+                }
+                _ => {
+                    match header.payload_len {
+                        Some(len) => try!(skip_bytes(r, len)),
+                        // A trailing box (e.g. `mdat`) extending to the end
+                        // of the file: nothing more to read either way.
+                        None => break,
+                    }
+                }
+            }
+
+            if dimensions.is_some() {
+                break;
+            }
+        }
+
+        if !brand_ok {
+            return Err(invalid_format!("not a HEIF/HEIC file (unrecognized major/compatible brand)"));
+        }
+
+        let dimensions = try!(dimensions.ok_or_else(|| invalid_format!("no ispe box found")));
+        Ok(Metadata { dimensions: dimensions })
+    }
+}
+
+// Reads an `ftyp` box payload and reports whether any of the brands we
+// recognize (major or compatible) are present.
+fn read_ftyp<R: ?Sized + BufRead>(r: &mut R, len: u64) -> Result<bool> {
+    if len < 8 || (len - 8) % 4 != 0 {
+        return Err(invalid_format!("invalid ftyp box size: {}", len));
+    }
+
+    let mut found = false;
+    let mut remaining = len;
+
+    // major_brand
+    let mut brand = [0; 4];
+    try!(r.read_exact(&mut brand));
+    if BRANDS.iter().any(|b| **b == brand) {
+        found = true;
+    }
+    remaining -= 4;
+
+    // minor_version
+    try!(skip_bytes(r, 4));
+    remaining -= 4;
+
+    // compatible_brands
+    while remaining > 0 {
+        try!(r.read_exact(&mut brand));
+        if BRANDS.iter().any(|b| **b == brand) {
+            found = true;
+        }
+        remaining -= 4;
+    }
+
+    Ok(found)
+}
+
+// `meta` is a FullBox (4-byte version/flags) whose children we care about
+// are nested under `iprp` -> `ipco` -> `ispe`.
+fn read_meta<R: ?Sized + BufRead>(r: &mut R, len: u64) -> Result<Option<Dimensions>> {
+    if len < 4 {
+        return Err(invalid_format!("invalid meta box size: {}", len));
+    }
+    try!(skip_bytes(r, 4));
+
+    let mut dimensions = None;
+    try!(walk_boxes(r, Some(len - 4), |r, box_type, payload_len| {
+        if &box_type == b"iprp" {
+            let len = try!(payload_len.ok_or_else(
+                || invalid_format!("iprp box must not extend to end of file")));
+            dimensions = try!(read_iprp(r, len));
+        } else if let Some(len) = payload_len {
+            try!(skip_bytes(r, len));
+        } else {
+            return Err(invalid_format!("unexpected meta child box extending to end of file"));
+        }
+        Ok(())
+    }));
+
+    Ok(dimensions)
+}
+
+fn read_iprp<R: ?Sized + BufRead>(r: &mut R, len: u64) -> Result<Option<Dimensions>> {
+    let mut dimensions = None;
+    try!(walk_boxes(r, Some(len), |r, box_type, payload_len| {
+        if &box_type == b"ipco" {
+            let len = try!(payload_len.ok_or_else(
+                || invalid_format!("ipco box must not extend to end of file")));
+            dimensions = try!(read_ipco(r, len));
+        } else if let Some(len) = payload_len {
+            try!(skip_bytes(r, len));
+        } else {
+            return Err(invalid_format!("unexpected iprp child box extending to end of file"));
+        }
+        Ok(())
+    }));
+    Ok(dimensions)
+}
+
+fn read_ipco<R: ?Sized + BufRead>(r: &mut R, len: u64) -> Result<Option<Dimensions>> {
+    let mut dimensions = None;
+    try!(walk_boxes(r, Some(len), |r, box_type, payload_len| {
+        if &box_type == b"ispe" {
+            let len = try!(payload_len.ok_or_else(
+                || invalid_format!("ispe box must not extend to end of file")));
+            if len < 12 {
+                return Err(invalid_format!("invalid ispe box size: {}", len));
+            }
+            try!(skip_bytes(r, 4));  // version/flags
+            let width = try_if_eof!(r.read_u32::<BigEndian>(), "when reading ispe width");
+            let height = try_if_eof!(r.read_u32::<BigEndian>(), "when reading ispe height");
+            try!(skip_bytes(r, len - 12));
+            dimensions = Some((width, height).into());
+        } else if let Some(len) = payload_len {
+            try!(skip_bytes(r, len));
+        } else {
+            return Err(invalid_format!("unexpected ipco child box extending to end of file"));
+        }
+        Ok(())
+    }));
+    Ok(dimensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sized_box(box_type: &[u8; 4], payload: Vec<u8>) -> Vec<u8> {
+        let mut b = Vec::new();
+        b.extend_from_slice(&((8 + payload.len()) as u32).to_be_bytes());
+        b.extend_from_slice(box_type);
+        b.extend_from_slice(&payload);
+        b
+    }
+
+    fn ispe_box(width: u32, height: u32) -> Vec<u8> {
+        let mut payload = vec![0u8; 4]; // version/flags
+        payload.extend_from_slice(&width.to_be_bytes());
+        payload.extend_from_slice(&height.to_be_bytes());
+        sized_box(b"ispe", payload)
+    }
+
+    fn heic_ftyp_meta(width: u32, height: u32) -> Vec<u8> {
+        let ipco = sized_box(b"ipco", ispe_box(width, height));
+        let iprp = sized_box(b"iprp", ipco);
+        let mut meta_payload = vec![0u8; 4]; // version/flags
+        meta_payload.extend_from_slice(&iprp);
+
+        let mut buf = sized_box(b"ftyp", b"heic\0\0\0\0".to_vec());
+        buf.extend(sized_box(b"meta", meta_payload));
+        buf
+    }
+
+    #[test]
+    fn parses_ispe_dimensions_with_sized_mdat() {
+        let mut buf = heic_ftyp_meta(4032, 3024);
+        buf.extend(sized_box(b"mdat", vec![0u8; 16]));
+
+        let meta = Metadata::load(&mut buf.as_slice()).unwrap();
+        assert_eq!(meta.dimensions, Dimensions { width: 4032, height: 3024 });
+    }
+
+    #[test]
+    fn trailing_to_eof_mdat_is_not_an_error() {
+        let mut buf = heic_ftyp_meta(4032, 3024);
+        // size == 0 means "extends to end of file"; the trailing bytes
+        // stand in for pixel data we must not need to read.
+        buf.extend_from_slice(&0u32.to_be_bytes());
+        buf.extend_from_slice(b"mdat");
+        buf.extend_from_slice(&[0xffu8; 64]);
+
+        let meta = Metadata::load(&mut buf.as_slice()).unwrap();
+        assert_eq!(meta.dimensions, Dimensions { width: 4032, height: 3024 });
+    }
+
+    #[test]
+    fn box_size_smaller_than_header_is_rejected_not_a_panic() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&5u32.to_be_bytes());
+        buf.extend_from_slice(b"ftyp");
+
+        match Metadata::load(&mut buf.as_slice()) {
+            Err(Error::InvalidFormat(..)) => {}
+            other => panic!("expected InvalidFormat, got {:?}", other),
+        }
+    }
+}
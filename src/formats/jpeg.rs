@@ -3,7 +3,7 @@
 use std::io::BufRead;
 use std::fmt;
 
-use byteorder::{ReadBytesExt, BigEndian};
+use byteorder::{ReadBytesExt, ByteOrder, BigEndian, LittleEndian};
 
 use types::{Result, Dimensions, Error};
 use traits::LoadableMetadata;
@@ -70,6 +70,122 @@ impl EntropyCoding {
     }
 }
 
+/// Orientation of an image, as recorded by a camera in its EXIF data.
+///
+/// This describes the rotation and mirroring needed to display the image
+/// the right way up; `immeta` does not apply it, it merely reports it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Orientation {
+    /// Normal orientation, no transformation needed.
+    Normal,
+    /// Mirrored horizontally.
+    FlipHorizontal,
+    /// Rotated 180°.
+    Rotate180,
+    /// Mirrored vertically.
+    FlipVertical,
+    /// Mirrored horizontally, then rotated 270° clockwise.
+    Transpose,
+    /// Rotated 90° clockwise.
+    Rotate90,
+    /// Mirrored horizontally, then rotated 90° clockwise.
+    Transverse,
+    /// Rotated 270° clockwise.
+    Rotate270,
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Orientation::Normal => "normal",
+            Orientation::FlipHorizontal => "flipped horizontally",
+            Orientation::Rotate180 => "rotated 180°",
+            Orientation::FlipVertical => "flipped vertically",
+            Orientation::Transpose => "transposed",
+            Orientation::Rotate90 => "rotated 90°",
+            Orientation::Transverse => "transversed",
+            Orientation::Rotate270 => "rotated 270°",
+        })
+    }
+}
+
+impl Orientation {
+    fn from_tiff_value(value: u16) -> Option<Orientation> {
+        match value {
+            1 => Some(Orientation::Normal),
+            2 => Some(Orientation::FlipHorizontal),
+            3 => Some(Orientation::Rotate180),
+            4 => Some(Orientation::FlipVertical),
+            5 => Some(Orientation::Transpose),
+            6 => Some(Orientation::Rotate90),
+            7 => Some(Orientation::Transverse),
+            8 => Some(Orientation::Rotate270),
+            _ => None,
+        }
+    }
+}
+
+/// Chroma subsampling scheme used by an image, derived from the horizontal
+/// and vertical sampling factors of its frame components.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ChromaSubsampling {
+    /// No subsampling: chroma channels sampled at full luma resolution.
+    Ycc444,
+    /// Chroma subsampled by half horizontally.
+    Ycc422,
+    /// Chroma subsampled by half both horizontally and vertically.
+    Ycc420,
+    /// Chroma subsampled by a quarter horizontally.
+    Ycc411,
+    /// Single-component (grayscale) image; no chroma channels at all.
+    Grayscale,
+    /// A subsampling scheme other than the common ones above.
+    Other,
+}
+
+impl fmt::Display for ChromaSubsampling {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ChromaSubsampling::Ycc444 => "4:4:4",
+            ChromaSubsampling::Ycc422 => "4:2:2",
+            ChromaSubsampling::Ycc420 => "4:2:0",
+            ChromaSubsampling::Ycc411 => "4:1:1",
+            ChromaSubsampling::Grayscale => "grayscale",
+            ChromaSubsampling::Other => "other",
+        })
+    }
+}
+
+impl ChromaSubsampling {
+    // `components` holds the (horizontal, vertical) sampling factors of
+    // each frame component, in the order they appear in the SOF marker; the
+    // first component is always luma.
+    fn from_components(components: &[(u8, u8)]) -> ChromaSubsampling {
+        if components.len() < 2 {
+            return ChromaSubsampling::Grayscale;
+        }
+
+        let (luma_h, luma_v) = components[0];
+        let chroma = components[1];
+        if !components[1..].iter().all(|&c| c == chroma) {
+            return ChromaSubsampling::Other;
+        }
+
+        let (chroma_h, chroma_v) = chroma;
+        if chroma_h == 0 || chroma_v == 0 {
+            return ChromaSubsampling::Other;
+        }
+
+        match (luma_h / chroma_h, luma_v / chroma_v) {
+            (1, 1) => ChromaSubsampling::Ycc444,
+            (2, 1) => ChromaSubsampling::Ycc422,
+            (2, 2) => ChromaSubsampling::Ycc420,
+            (4, 1) => ChromaSubsampling::Ycc411,
+            _ => ChromaSubsampling::Other,
+        }
+    }
+}
+
 /// Represents metadata of a JPEG image.
 ///
 /// It provides information contained in JPEG frame header, including image dimensions,
@@ -88,6 +204,16 @@ pub struct Metadata {
     pub baseline: bool,
     /// Whether this image uses a differential encoding.
     pub differential: bool,
+    /// Orientation recorded in the image's EXIF data, if any.
+    pub orientation: Option<Orientation>,
+    /// Number of components (e.g. 3 for YCbCr, 1 for grayscale) in the frame.
+    pub num_components: u8,
+    /// Chroma subsampling scheme derived from the components' sampling factors.
+    pub chroma_subsampling: ChromaSubsampling,
+    /// Estimated encoder quality (1-100), derived from the DQT quantization
+    /// tables. `None` if the stream has no DQT segment (e.g. an abbreviated
+    /// stream that references external tables).
+    pub quality: Option<u8>,
 }
 
 fn find_marker<R: ?Sized, F>(r: &mut R, name: &str, mut matcher: F) -> Result<u8>
@@ -129,17 +255,31 @@ impl LoadableMetadata for Metadata {
         // XXX: determine the JPEG container that is used
         match try!(container_type_from_app_marker(app_marker)) {
             ContainerType::JFIF => load_jfif(r),
-            //ContainerType::EXIF => Err(invalid_format!("EXIF file format is not supported"))
-            ContainerType::EXIF => load_exif(r)
+            ContainerType::EXIF => load_exif(r, length)
         }
 
     }
 
 }
 
+// There may be several DQT (quantization table) segments before the SOF
+// marker; we scan past them just like any other marker, but peek into
+// their payload to recover an approximate encoder quality.
+fn find_sof<R: ?Sized + BufRead>(r: &mut R) -> Result<(u8, Option<u8>)> {
+    let mut quality = None;
+    loop {
+        let marker = try!(find_marker(r, "SOF", |m| is_sof_marker(m) || m == 0xdb));
+        if marker == 0xdb {
+            quality = try!(read_dqt(r)).or(quality);
+            continue;
+        }
+        return Ok((marker, quality));
+    }
+}
+
 fn load_jfif<R: ?Sized + BufRead>(r: &mut R) -> Result<Metadata> {
     // read SOF marker, it must also be present in all JPEG files
-    let marker = try!(find_marker(r, "SOF", is_sof_marker));
+    let (marker, quality) = try!(find_sof(r));
 
     // read and check SOF marker length
     let size = try_if_eof!(r.read_u16::<BigEndian>(), "when reading SOF marker payload size");
@@ -159,6 +299,18 @@ fn load_jfif<R: ?Sized + BufRead>(r: &mut R) -> Result<Metadata> {
     let w = try_if_eof!(r.read_u16::<BigEndian>(), "when reading JPEG frame width");
     // TODO: handle h == 0 (we need to read a DNL marker after the first scan)
 
+    // read the component table: one sampling-factor pair per component,
+    // the first component is always luma
+    let num_components = try_if_eof!(r.read_u8(), "when reading number of components in the frame");
+    let mut components = Vec::with_capacity(num_components as usize);
+    for _ in 0..num_components {
+        try_if_eof!(r.read_u8(), "when reading component identifier");  // component id, unused
+        let sampling_factors = try_if_eof!(r.read_u8(), "when reading component sampling factors");
+        try_if_eof!(r.read_u8(), "when reading component quantization table selector");  // unused
+        components.push((sampling_factors >> 4, sampling_factors & 0x0f));
+    }
+    let chroma_subsampling = ChromaSubsampling::from_components(&components);
+
     // there is only one baseline DCT marker, naturally
     let baseline = marker == 0xc0;
 
@@ -179,12 +331,73 @@ fn load_jfif<R: ?Sized + BufRead>(r: &mut R) -> Result<Metadata> {
         entropy_coding: entropy_coding,
         baseline: baseline,
         differential: differential,
+        orientation: None,
+        num_components: num_components,
+        chroma_subsampling: chroma_subsampling,
+        quality: quality,
     })
 }
-fn load_exif<R: ?Sized + BufRead>(r: &mut R) -> Result<Metadata> {
+
+// Sum of the standard IJG luminance quantization table at quality 50,
+// used as the reference point when inverting the quality formula below.
+const BASELINE_LUMA_TABLE_SUM: u32 = 3688;
+const LUMA_QUANTIZATION_TABLE_ID: u8 = 0;
+const QUANTIZATION_TABLE_ELEMENT_COUNT: usize = 64;
+
+// Reads a DQT (Define Quantization Table) segment, which may contain one or
+// more tables, and returns the estimated quality of the luma table (id 0)
+// if one was present.
+fn read_dqt<R: ?Sized + BufRead>(r: &mut R) -> Result<Option<u8>> {
+    let length = try_if_eof!(r.read_u16::<BigEndian>(), "when reading DQT marker payload size");
+    if length <= 2 {
+        return Err(invalid_format!("invalid DQT marker length: {}", length));
+    }
+
+    let mut remaining = length as i32 - 2;
+    let mut luma_quality = None;
+
+    while remaining > 0 {
+        let precision_and_id = try_if_eof!(r.read_u8(), "when reading DQT table precision/id");
+        remaining -= 1;
+        let precision = precision_and_id >> 4;
+        let table_id = precision_and_id & 0x0f;
+
+        let mut sum: u32 = 0;
+        for _ in 0..QUANTIZATION_TABLE_ELEMENT_COUNT {
+            if precision == 0 {
+                sum += try_if_eof!(r.read_u8(), "when reading DQT coefficient") as u32;
+                remaining -= 1;
+            } else {
+                sum += try_if_eof!(r.read_u16::<BigEndian>(), "when reading DQT coefficient") as u32;
+                remaining -= 2;
+            }
+        }
+
+        if table_id == LUMA_QUANTIZATION_TABLE_ID {
+            luma_quality = Some(quality_from_table_sum(sum));
+        }
+    }
+
+    Ok(luma_quality)
+}
+
+// Inverts the IJG quality formula (quality < 50: scale = 5000 / quality;
+// quality >= 50: scale = 200 - 2 * quality) using the ratio of the table's
+// total to the baseline reference table's total in place of `scale`.
+fn quality_from_table_sum(sum: u32) -> u8 {
+    let scale_percent = sum as f64 / BASELINE_LUMA_TABLE_SUM as f64 * 100.0;
+    let quality = if scale_percent <= 100.0 {
+        (200.0 - scale_percent) / 2.0
+    } else {
+        5000.0 / scale_percent
+    };
+    quality.round().max(1.0).min(100.0) as u8
+}
+
+fn load_exif<R: ?Sized + BufRead>(r: &mut R, segment_length: u16) -> Result<Metadata> {
     // The first 6 bytes should be the string "EXIF" followed by two null bytes.
     // If this is not the case, we don't have an EXIF file
-    const EXIF_IDENTIFIER_LENGTH: usize= 6;
+    const EXIF_IDENTIFIER_LENGTH: usize = 6;
     let mut buffer = [0; EXIF_IDENTIFIER_LENGTH];
     try!(r.read_exact(&mut buffer));
     if buffer != [0x45, 0x78, 0x69, 0x66, 0x00, 0x00]
@@ -192,12 +405,147 @@ fn load_exif<R: ?Sized + BufRead>(r: &mut R) -> Result<Metadata> {
         return Err(invalid_format!("JPEG file with APP1 marker is not EXIF"));
     }
 
-    //Reading the byte allign of the exif content. Some EIXF files use big
+    // `segment_length` counts the 2-byte length field itself and the
+    // identifier we just read; the rest is the TIFF structure. We buffer it
+    // in full because every offset inside it is relative to the start of
+    // the TIFF header, so the reader needs to be able to seek backwards.
+    let tiff_length = segment_length as usize - 2 - EXIF_IDENTIFIER_LENGTH;
+    let mut tiff = vec![0; tiff_length];
+    try!(r.read_exact(&mut tiff));
+
+    if tiff.len() < 8 {
+        return Err(unexpected_eof!("when reading TIFF header"));
+    }
+
+    //Reading the byte align of the exif content. Some EXIF files use big
     //endian while some use little endian files
-    unimplemented!()
+    let ifd_data = match (tiff[0], tiff[1]) {
+        (0x49, 0x49) => try!(load_exif_with_endianness::<LittleEndian>(&tiff)),
+        (0x4d, 0x4d) => try!(load_exif_with_endianness::<BigEndian>(&tiff)),
+        _ => return Err(invalid_format!("invalid TIFF byte order mark")),
+    };
+
+    // The actual frame header (SOF) still follows the EXIF segment in the
+    // JPEG stream, so the rest of `Metadata` is read exactly like JFIF.
+    let mut metadata = try!(load_jfif(r));
+    metadata.orientation = ifd_data.orientation;
+
+    // TODO: handle h == 0 in load_jfif properly (we need to read a DNL
+    // marker after the first scan); until then, fall back to the
+    // dimensions recorded in the EXIF data when SOF didn't have them.
+    if metadata.dimensions.height == 0 {
+        if let (Some(width), Some(height)) = (ifd_data.width, ifd_data.height) {
+            metadata.dimensions = (width, height).into();
+        }
+    }
+
+    Ok(metadata)
+}
+
+/// The handful of TIFF tags `load_exif` understands.
+#[derive(Default, Debug)]
+struct IfdData {
+    orientation: Option<Orientation>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+const TIFF_TAG_ORIENTATION: u16 = 0x0112;
+const TIFF_TAG_EXIF_IMAGE_WIDTH: u16 = 0xa002;
+const TIFF_TAG_EXIF_IMAGE_HEIGHT: u16 = 0xa003;
+const TIFF_TAG_EXIF_IFD_POINTER: u16 = 0x8769;
+
+const TIFF_TYPE_SHORT: u16 = 3;
+const TIFF_TYPE_LONG: u16 = 4;
+
+// A sane TIFF has at most a handful of IFDs; bound the walk so a
+// malformed "next IFD" offset that loops back on itself can't hang the
+// caller.
+const MAX_IFD_COUNT: usize = 32;
+
+// Reads the TIFF header and walks the IFD0 chain (and the Exif sub-IFD it
+// points to, if any), collecting the tags we're interested in.
+fn load_exif_with_endianness<E: ByteOrder>(tiff: &[u8]) -> Result<IfdData> {
+    let magic = E::read_u16(&tiff[2..4]);
+    if magic != 42 {
+        return Err(invalid_format!("invalid TIFF magic number: {}", magic));
+    }
+
+    let mut data = IfdData::default();
+    let mut exif_ifd_offset = None;
+
+    let mut ifd_offset = E::read_u32(&tiff[4..8]) as usize;
+    let mut ifd_count = 0;
+    while ifd_offset != 0 {
+        if ifd_count >= MAX_IFD_COUNT {
+            return Err(invalid_format!("too many IFDs (possible offset loop)"));
+        }
+        ifd_offset = try!(read_ifd::<E>(tiff, ifd_offset, &mut data, &mut exif_ifd_offset));
+        ifd_count += 1;
+    }
+
+    if let Some(offset) = exif_ifd_offset {
+        try!(read_ifd::<E>(tiff, offset, &mut data, &mut None));
+    }
+
+    Ok(data)
+}
+
+// Reads a single Image File Directory, filling in any recognized tags, and
+// returns the offset of the next IFD (0 if there is none).
+fn read_ifd<E: ByteOrder>(tiff: &[u8],
+                          offset: usize,
+                          data: &mut IfdData,
+                          exif_ifd_offset: &mut Option<usize>)
+                          -> Result<usize> {
+    if offset + 2 > tiff.len() {
+        return Err(unexpected_eof!("when reading IFD entry count"));
+    }
+    let entry_count = E::read_u16(&tiff[offset..offset + 2]) as usize;
+
+    for i in 0..entry_count {
+        let entry_offset = offset + 2 + i * 12;
+        if entry_offset + 12 > tiff.len() {
+            return Err(unexpected_eof!("when reading IFD entry"));
+        }
+        let entry = &tiff[entry_offset..entry_offset + 12];
+        let tag = E::read_u16(&entry[0..2]);
+        let value_type = E::read_u16(&entry[2..4]);
+        let value = &entry[8..12];
+
+        match tag {
+            TIFF_TAG_ORIENTATION => {
+                data.orientation = Orientation::from_tiff_value(E::read_u16(&value[0..2]));
+            }
+            TIFF_TAG_EXIF_IMAGE_WIDTH => {
+                data.width = read_inline_value::<E>(value_type, value);
+            }
+            TIFF_TAG_EXIF_IMAGE_HEIGHT => {
+                data.height = read_inline_value::<E>(value_type, value);
+            }
+            TIFF_TAG_EXIF_IFD_POINTER => {
+                *exif_ifd_offset = Some(E::read_u32(value) as usize);
+            }
+            _ => {}
+        }
+    }
+
+    let next_ifd_offset = offset + 2 + entry_count * 12;
+    if next_ifd_offset + 4 > tiff.len() {
+        return Err(unexpected_eof!("when reading next IFD offset"));
+    }
+    Ok(E::read_u32(&tiff[next_ifd_offset..next_ifd_offset + 4]) as usize)
 }
 
-fn load_exif_with_endianness<R: ?Sized + BufRead, E: >
+// Interprets a 4-byte "value or offset" field as an inline SHORT or LONG.
+// Other types aren't needed by any tag we currently read.
+fn read_inline_value<E: ByteOrder>(value_type: u16, value: &[u8]) -> Option<u32> {
+    match value_type {
+        TIFF_TYPE_SHORT => Some(E::read_u16(&value[0..2]) as u32),
+        TIFF_TYPE_LONG => Some(E::read_u32(value)),
+        _ => None,
+    }
+}
 
 fn is_sof_marker(value: u8) -> bool {
     match value {
@@ -235,3 +583,94 @@ enum ContainerType {
     EXIF
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chroma_subsampling_from_components() {
+        assert_eq!(ChromaSubsampling::from_components(&[(2, 2), (1, 1), (1, 1)]),
+                   ChromaSubsampling::Ycc420);
+        assert_eq!(ChromaSubsampling::from_components(&[(2, 1), (1, 1), (1, 1)]),
+                   ChromaSubsampling::Ycc422);
+        assert_eq!(ChromaSubsampling::from_components(&[(1, 1)]),
+                   ChromaSubsampling::Grayscale);
+    }
+
+    #[test]
+    fn chroma_subsampling_zero_sampling_factor_is_other_not_a_panic() {
+        assert_eq!(ChromaSubsampling::from_components(&[(2, 2), (0, 0), (0, 0)]),
+                   ChromaSubsampling::Other);
+    }
+
+    #[test]
+    fn quality_from_table_sum_round_trips_the_baseline() {
+        // The baseline table itself corresponds to quality 50 by definition.
+        assert_eq!(quality_from_table_sum(BASELINE_LUMA_TABLE_SUM), 50);
+    }
+
+    #[test]
+    fn read_dqt_reports_luma_table_quality() {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&(2u16 + 1 + QUANTIZATION_TABLE_ELEMENT_COUNT as u16).to_be_bytes());
+        payload.push(LUMA_QUANTIZATION_TABLE_ID); // precision 0, table id 0
+        // 64 coefficients summing to exactly BASELINE_LUMA_TABLE_SUM, so the
+        // estimated quality comes out to exactly 50.
+        let base = BASELINE_LUMA_TABLE_SUM / QUANTIZATION_TABLE_ELEMENT_COUNT as u32;
+        let remainder = BASELINE_LUMA_TABLE_SUM % QUANTIZATION_TABLE_ELEMENT_COUNT as u32;
+        for i in 0..QUANTIZATION_TABLE_ELEMENT_COUNT {
+            let coefficient = if (i as u32) < remainder { base + 1 } else { base };
+            payload.push(coefficient as u8);
+        }
+
+        let quality = read_dqt(&mut payload.as_slice()).unwrap();
+        assert_eq!(quality, Some(50));
+    }
+
+    fn tiff_header_with_ifd0(ifd0: &[u8]) -> Vec<u8> {
+        let mut tiff = Vec::new();
+        tiff.extend_from_slice(b"MM"); // byte order marker, unused by the code under test
+        tiff.extend_from_slice(&42u16.to_be_bytes());
+        tiff.extend_from_slice(&8u32.to_be_bytes()); // IFD0 starts right after the header
+        tiff.extend_from_slice(ifd0);
+        tiff
+    }
+
+    #[test]
+    fn load_exif_reads_orientation_tag() {
+        let mut ifd0 = Vec::new();
+        ifd0.extend_from_slice(&1u16.to_be_bytes()); // one entry
+        ifd0.extend_from_slice(&TIFF_TAG_ORIENTATION.to_be_bytes());
+        ifd0.extend_from_slice(&TIFF_TYPE_SHORT.to_be_bytes());
+        ifd0.extend_from_slice(&1u32.to_be_bytes()); // count
+        ifd0.extend_from_slice(&6u16.to_be_bytes()); // value: rotated 90°
+        ifd0.extend_from_slice(&[0, 0]); // padding to fill the 4-byte value field
+        ifd0.extend_from_slice(&0u32.to_be_bytes()); // no next IFD
+
+        let tiff = tiff_header_with_ifd0(&ifd0);
+        let data = load_exif_with_endianness::<BigEndian>(&tiff).unwrap();
+        assert_eq!(data.orientation, Some(Orientation::Rotate90));
+    }
+
+    #[test]
+    fn load_exif_rejects_an_ifd_chain_that_loops_back_on_itself() {
+        // IFD at offset 8 points to an IFD at offset 14, which points back
+        // to offset 8: a malformed file that must not hang the caller.
+        let mut ifd0 = Vec::new();
+        ifd0.extend_from_slice(&0u16.to_be_bytes()); // no entries
+        ifd0.extend_from_slice(&14u32.to_be_bytes()); // next IFD
+
+        let mut ifd1 = Vec::new();
+        ifd1.extend_from_slice(&0u16.to_be_bytes()); // no entries
+        ifd1.extend_from_slice(&8u32.to_be_bytes()); // back to IFD0
+
+        let mut tiff = tiff_header_with_ifd0(&ifd0);
+        tiff.extend_from_slice(&ifd1);
+
+        match load_exif_with_endianness::<BigEndian>(&tiff) {
+            Err(Error::InvalidFormat(..)) => {}
+            other => panic!("expected InvalidFormat, got {:?}", other),
+        }
+    }
+}
+
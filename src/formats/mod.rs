@@ -0,0 +1,6 @@
+//! Per-format metadata readers.
+
+pub mod jpeg;
+pub mod isobmff;
+pub mod png;
+pub mod gif;
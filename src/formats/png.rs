@@ -0,0 +1,41 @@
+//! Metadata of PNG images.
+
+use std::io::BufRead;
+
+use byteorder::{ReadBytesExt, BigEndian};
+
+use types::{Result, Dimensions};
+use traits::LoadableMetadata;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// Represents metadata of a PNG image.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    /// Image size.
+    pub dimensions: Dimensions,
+}
+
+impl LoadableMetadata for Metadata {
+    fn load<R: ?Sized + BufRead>(r: &mut R) -> Result<Metadata> {
+        let mut signature = [0; 8];
+        try!(r.read_exact(&mut signature));
+        if signature != PNG_SIGNATURE {
+            return Err(invalid_format!("invalid PNG signature"));
+        }
+
+        // the IHDR chunk is always first: 4-byte length, 4-byte type "IHDR",
+        // then width and height as big-endian u32s
+        let length = try_if_eof!(r.read_u32::<BigEndian>(), "when reading IHDR chunk length");
+        let mut chunk_type = [0; 4];
+        try!(r.read_exact(&mut chunk_type));
+        if &chunk_type != b"IHDR" || length < 8 {
+            return Err(invalid_format!("PNG file does not start with an IHDR chunk"));
+        }
+
+        let width = try_if_eof!(r.read_u32::<BigEndian>(), "when reading IHDR width");
+        let height = try_if_eof!(r.read_u32::<BigEndian>(), "when reading IHDR height");
+
+        Ok(Metadata { dimensions: (width, height).into() })
+    }
+}
@@ -0,0 +1,78 @@
+//! Top-level format dispatch: sniff a stream's magic bytes and parse it
+//! with the appropriate format-specific loader.
+
+use std::io::BufRead;
+
+use traits::LoadableMetadata;
+use types::{Dimensions, Result};
+use formats::{jpeg, png, gif, isobmff};
+
+/// Metadata of an image whose format was determined automatically by
+/// [`load_any`](fn.load_any.html).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GenericMetadata {
+    /// A JPEG image.
+    Jpeg(jpeg::Metadata),
+    /// A PNG image.
+    Png(png::Metadata),
+    /// A GIF image.
+    Gif(gif::Metadata),
+    /// A HEIF/HEIC (ISOBMFF) image.
+    Heif(isobmff::Metadata),
+}
+
+impl GenericMetadata {
+    /// Returns the dimensions of the image, regardless of its format.
+    pub fn dimensions(&self) -> Dimensions {
+        match *self {
+            GenericMetadata::Jpeg(ref m) => m.dimensions,
+            GenericMetadata::Png(ref m) => m.dimensions,
+            GenericMetadata::Gif(ref m) => m.dimensions,
+            GenericMetadata::Heif(ref m) => m.dimensions,
+        }
+    }
+}
+
+const JPEG_SIGNATURE: [u8; 2] = [0xff, 0xd8];
+const PNG_SIGNATURE: [u8; 8] = [0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
+const GIF_SIGNATURE: [u8; 4] = [0x47, 0x49, 0x46, 0x38];
+
+enum Format {
+    Jpeg,
+    Png,
+    Gif,
+    Heif,
+}
+
+fn sniff_format(prefix: &[u8]) -> Result<Format> {
+    if prefix.starts_with(&JPEG_SIGNATURE) {
+        Ok(Format::Jpeg)
+    } else if prefix.starts_with(&PNG_SIGNATURE) {
+        Ok(Format::Png)
+    } else if prefix.starts_with(&GIF_SIGNATURE) {
+        Ok(Format::Gif)
+    } else if prefix.len() >= 8 && &prefix[4..8] == b"ftyp" {
+        Ok(Format::Heif)
+    } else {
+        Err(invalid_format!("unrecognized image format"))
+    }
+}
+
+/// Sniffs the format of an image from the leading bytes of `r` and parses
+/// its metadata with the matching format-specific loader.
+///
+/// This uses `BufRead::fill_buf` to peek at the stream, so each concrete
+/// loader still sees the stream from the very beginning.
+pub fn load_any<R: ?Sized + BufRead>(r: &mut R) -> Result<GenericMetadata> {
+    let format = {
+        let prefix = try!(r.fill_buf());
+        try!(sniff_format(prefix))
+    };
+
+    match format {
+        Format::Jpeg => jpeg::Metadata::load(r).map(GenericMetadata::Jpeg),
+        Format::Png => png::Metadata::load(r).map(GenericMetadata::Png),
+        Format::Gif => gif::Metadata::load(r).map(GenericMetadata::Gif),
+        Format::Heif => isobmff::Metadata::load(r).map(GenericMetadata::Heif),
+    }
+}
@@ -0,0 +1,18 @@
+//! `immeta` reads just enough of an image file to report its metadata
+//! (dimensions and other format-specific details) without decoding any
+//! pixel data.
+
+extern crate byteorder;
+
+#[macro_use]
+mod macros;
+
+pub mod types;
+pub mod traits;
+mod utils;
+pub mod formats;
+mod generic;
+
+pub use types::{Dimensions, Error, Result};
+pub use traits::LoadableMetadata;
+pub use generic::{GenericMetadata, load_any};
@@ -0,0 +1,27 @@
+//! Helper macros for constructing `Error` values with contextual messages.
+
+macro_rules! invalid_format {
+    ($($arg:tt)*) => {
+        $crate::types::Error::InvalidFormat(format!($($arg)*))
+    }
+}
+
+macro_rules! unexpected_eof {
+    ($($arg:tt)*) => {
+        $crate::types::Error::UnexpectedEof(format!($($arg)*))
+    }
+}
+
+// Turns an `io::Result` into our `Result`, mapping an EOF specifically into
+// an `UnexpectedEof` carrying the caller-supplied context instead of the
+// generic io::Error message.
+macro_rules! try_if_eof {
+    ($e:expr, $($arg:tt)*) => {
+        match $e {
+            Ok(v) => v,
+            Err(ref e) if e.kind() == ::std::io::ErrorKind::UnexpectedEof =>
+                return Err(unexpected_eof!($($arg)*)),
+            Err(e) => return Err(From::from(e)),
+        }
+    }
+}
@@ -0,0 +1,11 @@
+//! Core trait implemented by every format-specific `Metadata` type.
+
+use std::io::BufRead;
+
+use types::Result;
+
+/// Implemented by the `Metadata` type of every supported format.
+pub trait LoadableMetadata: Sized {
+    /// Reads and parses metadata from the given stream.
+    fn load<R: ?Sized + BufRead>(r: &mut R) -> Result<Self>;
+}
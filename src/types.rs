@@ -0,0 +1,78 @@
+//! Basic types shared by all format-specific metadata readers.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+/// The error type returned when metadata cannot be read from a stream.
+#[derive(Debug)]
+pub enum Error {
+    /// An I/O error occurred while reading the stream.
+    Io(io::Error),
+    /// The stream ended before a complete piece of data could be read.
+    UnexpectedEof(String),
+    /// The stream does not contain data in the expected format.
+    InvalidFormat(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::UnexpectedEof(ref s) => write!(f, "unexpected end of file {}", s),
+            Error::InvalidFormat(ref s) => write!(f, "invalid format: {}", s),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(ref e) => e.description(),
+            Error::UnexpectedEof(..) => "unexpected end of file",
+            Error::InvalidFormat(..) => "invalid format",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error { Error::Io(e) }
+}
+
+/// The result type returned by metadata loaders.
+pub type Result<T> = result::Result<T, Error>;
+
+/// Width and height of an image, in pixels.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Dimensions {
+    /// Image width, in pixels.
+    pub width: u32,
+    /// Image height, in pixels.
+    pub height: u32,
+}
+
+impl From<(u16, u16)> for Dimensions {
+    fn from((width, height): (u16, u16)) -> Dimensions {
+        Dimensions { width: width as u32, height: height as u32 }
+    }
+}
+
+impl From<(u32, u32)> for Dimensions {
+    fn from((width, height): (u32, u32)) -> Dimensions {
+        Dimensions { width: width, height: height }
+    }
+}
+
+impl fmt::Display for Dimensions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
@@ -0,0 +1,37 @@
+//! Small helpers layered on top of `std::io::BufRead`.
+
+use std::io::{self, BufRead};
+
+/// Extension methods used by the format loaders.
+pub trait BufReadExt: BufRead {
+    /// Reads and discards bytes until `byte` is found. Returns the number of
+    /// bytes consumed, including `byte` itself, or 0 if the stream ended
+    /// first.
+    fn skip_until(&mut self, byte: u8) -> io::Result<usize>;
+}
+
+impl<R: BufRead + ?Sized> BufReadExt for R {
+    fn skip_until(&mut self, byte: u8) -> io::Result<usize> {
+        let mut read = 0;
+        loop {
+            let (done, used) = {
+                let available = match self.fill_buf() {
+                    Ok(n) => n,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                match available.iter().position(|&b| b == byte) {
+                    Some(i) => (true, i + 1),
+                    None => (false, available.len()),
+                }
+            };
+            self.consume(used);
+            read += used;
+            if done {
+                return Ok(read);
+            } else if used == 0 {
+                return Ok(0);
+            }
+        }
+    }
+}